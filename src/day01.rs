@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::{env, time::Instant};
 
 const _EXAMPLE: &str = r"L68
 L30
@@ -12,42 +12,357 @@ R14
 L82
 ";
 
-fn parse(input: &str) -> Vec<i32> {
-    input
+const DIAL_SIZE: i32 = 100;
+const START: i32 = 50;
+
+#[derive(Debug)]
+enum Instruction {
+    Rotate(i32),
+    Set {
+        position: i32,
+        direction: Option<i32>,
+    },
+    Repeat(u64, Vec<Instruction>),
+}
+
+fn parse_instruction<'a>(token: &str, tokens: &mut impl Iterator<Item = &'a str>) -> Instruction {
+    if let Some(count) = token.strip_suffix("x")
+        && let Ok(count) = count.parse()
+    {
+        assert_eq!(tokens.next(), Some("("), "expected a block after {token}");
+        let mut body = Vec::new();
+        loop {
+            match tokens.next() {
+                Some(")") => break,
+                Some(token) => body.push(parse_instruction(token, tokens)),
+                None => panic!("unclosed repeat block"),
+            }
+        }
+        return Instruction::Repeat(count, body);
+    }
+
+    let set = |position: &str, direction| {
+        let position = position.parse().unwrap();
+        assert!(
+            (0..DIAL_SIZE).contains(&position),
+            "position {position} is not on the dial"
+        );
+        Instruction::Set {
+            position,
+            direction,
+        }
+    };
+    match (&token[..1], &token[1..]) {
+        ("=", position) => set(position, None),
+        ("R", num) if num.starts_with("=") => set(&num[1..], Some(1)),
+        ("L", num) if num.starts_with("=") => set(&num[1..], Some(-1)),
+        ("R", num) => Instruction::Rotate(num.parse().unwrap()),
+        ("L", num) => Instruction::Rotate(-num.parse::<i32>().unwrap()),
+        _ => panic!("malformed instruction {token}"),
+    }
+}
+
+fn parse_lock(input: &str) -> Vec<(usize, Instruction)> {
+    let source = input
         .lines()
-        .map(|line| match (&line[..1], &line[1..]) {
-            ("R", num) => num.parse().unwrap(),
-            ("L", num) => -num.parse::<i32>().unwrap(),
-            _ => panic!("malformed input"),
+        .map(|line| line.split("#").next().unwrap())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replace("(", " ( ")
+        .replace(")", " ) ");
+    let mut tokens = source.split_whitespace();
+    let mut program = Vec::new();
+    while let Some(token) = tokens.next() {
+        let (dial, token) = match token.split_once(":") {
+            Some((dial, token)) => (dial.parse().unwrap(), token),
+            None => (0, token),
+        };
+        program.push((dial, parse_instruction(token, &mut tokens)));
+    }
+    program
+}
+
+fn parse(input: &str) -> Vec<Instruction> {
+    parse_lock(input)
+        .into_iter()
+        .map(|(dial, instruction)| {
+            assert_eq!(dial, 0, "dial prefixes are only allowed in lock mode");
+            instruction
         })
         .collect()
 }
 
-fn part1(input: &Vec<i32>) -> u32 {
-    let mut password = 0;
-    let mut dial = 50;
-    for x in input {
-        dial = (dial + x).rem_euclid(100);
-        password += u32::from(dial == 0);
+fn rotation(dial: i32, instruction: &Instruction) -> i32 {
+    match *instruction {
+        Instruction::Rotate(x) => x,
+        Instruction::Set {
+            position,
+            direction: Some(direction),
+        } => direction * ((position - dial) * direction).rem_euclid(DIAL_SIZE),
+        Instruction::Set {
+            position,
+            direction: None,
+        } => {
+            let x = (position - dial).rem_euclid(DIAL_SIZE);
+            if x > DIAL_SIZE / 2 { x - DIAL_SIZE } else { x }
+        }
+        Instruction::Repeat(..) => unreachable!(),
     }
-    password
 }
 
-fn part2(input: &Vec<i32>) -> u32 {
-    let mut password = 0;
-    let mut dial = 50;
-    for x in input {
-        let next = dial + x;
-        let rotations = (next / 100).unsigned_abs();
-        let crosses_zero = next == 0 || dial.signum() + next.signum() == 0;
-        password += rotations + u32::from(crosses_zero);
-        dial = next.rem_euclid(100);
+fn passes(dial: i32, x: i32, target: i32) -> u32 {
+    let first = match ((target - dial) * x.signum()).rem_euclid(DIAL_SIZE) {
+        0 => DIAL_SIZE,
+        first => first,
+    };
+    let distance = x.abs();
+    if first >= distance {
+        0
+    } else {
+        ((distance - 1 - first) / DIAL_SIZE + 1) as u32
     }
-    password
+}
+
+#[derive(Clone)]
+struct Wear {
+    landings: Vec<u64>,
+    passes: Vec<u64>,
+}
+
+impl Wear {
+    fn new() -> Self {
+        Self {
+            landings: vec![0; DIAL_SIZE as usize],
+            passes: vec![0; DIAL_SIZE as usize],
+        }
+    }
+
+    fn visits(&self, position: usize) -> u64 {
+        self.landings[position] + self.passes[position]
+    }
+
+    fn turn(&mut self, dial: &mut i32, x: i32) {
+        for (target, count) in self.passes.iter_mut().enumerate() {
+            *count += passes(*dial, x, target as i32) as u64;
+        }
+        *dial = (*dial + x).rem_euclid(DIAL_SIZE);
+        self.landings[*dial as usize] += 1;
+    }
+
+    fn add(&mut self, other: &Wear) {
+        for (a, b) in self.landings.iter_mut().zip(&other.landings) {
+            *a += b;
+        }
+        for (a, b) in self.passes.iter_mut().zip(&other.passes) {
+            *a += b;
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Effect {
+    end: Vec<i32>,
+    wear: Vec<Wear>,
+}
+
+impl Effect {
+    fn identity() -> Self {
+        Self {
+            end: (0..DIAL_SIZE).collect(),
+            wear: vec![Wear::new(); DIAL_SIZE as usize],
+        }
+    }
+
+    fn of(instruction: &Instruction) -> Self {
+        match instruction {
+            Instruction::Repeat(count, body) => body
+                .iter()
+                .fold(Effect::identity(), |effect, instruction| {
+                    effect.then(&Effect::of(instruction))
+                })
+                .repeat(*count),
+            _ => {
+                let mut effect = Effect::identity();
+                for start in 0..DIAL_SIZE as usize {
+                    let dial = &mut effect.end[start];
+                    effect.wear[start].turn(dial, rotation(*dial, instruction));
+                }
+                effect
+            }
+        }
+    }
+
+    fn then(&self, other: &Effect) -> Effect {
+        let mut effect = self.clone();
+        for start in 0..DIAL_SIZE as usize {
+            let mid = self.end[start] as usize;
+            effect.end[start] = other.end[mid];
+            effect.wear[start].add(&other.wear[mid]);
+        }
+        effect
+    }
+
+    fn repeat(&self, mut count: u64) -> Effect {
+        let mut effect = Effect::identity();
+        let mut base = self.clone();
+        while count > 0 {
+            if count & 1 == 1 {
+                effect = effect.then(&base);
+            }
+            base = base.then(&base);
+            count >>= 1;
+        }
+        effect
+    }
+}
+
+fn run<'a>(program: impl IntoIterator<Item = &'a Instruction>) -> Wear {
+    let mut wear = Wear::new();
+    let mut dial = START;
+    for instruction in program {
+        match instruction {
+            Instruction::Repeat(..) => {
+                let effect = Effect::of(instruction);
+                wear.add(&effect.wear[dial as usize]);
+                dial = effect.end[dial as usize];
+            }
+            _ => {
+                let x = rotation(dial, instruction);
+                wear.turn(&mut dial, x);
+            }
+        }
+    }
+    wear
+}
+
+fn zero<'a>(program: impl IntoIterator<Item = &'a Instruction>) -> (u64, u64) {
+    let (mut landings, mut crossings) = (0, 0);
+    let mut dial = START;
+    for instruction in program {
+        match instruction {
+            Instruction::Repeat(..) => {
+                let effect = Effect::of(instruction);
+                let wear = &effect.wear[dial as usize];
+                landings += wear.landings[0];
+                crossings += wear.passes[0];
+                dial = effect.end[dial as usize];
+            }
+            _ => {
+                let x = rotation(dial, instruction);
+                let hits = turn(&mut dial, x) as u64;
+                let landed = u64::from(dial == 0);
+                landings += landed;
+                crossings += hits - landed;
+            }
+        }
+    }
+    (landings, crossings)
+}
+
+fn part1<'a>(program: impl IntoIterator<Item = &'a Instruction>) -> u64 {
+    zero(program).0
+}
+
+fn part2<'a>(program: impl IntoIterator<Item = &'a Instruction>) -> u64 {
+    let (landings, crossings) = zero(program);
+    landings + crossings
+}
+
+fn turn(dial: &mut i32, x: i32) -> u32 {
+    let crossings = passes(*dial, x, 0);
+    *dial = (*dial + x).rem_euclid(DIAL_SIZE);
+    crossings + u32::from(*dial == 0)
+}
+
+fn lock_step(
+    dials: &mut [i32],
+    passwords: &mut [u64],
+    links: &[usize],
+    mut dial: usize,
+    instruction: &Instruction,
+) {
+    let mut crossings = match instruction {
+        Instruction::Repeat(..) => {
+            let effect = Effect::of(instruction);
+            let start = dials[dial] as usize;
+            dials[dial] = effect.end[start];
+            effect.wear[start].visits(0)
+        }
+        _ => {
+            let x = rotation(dials[dial], instruction);
+            turn(&mut dials[dial], x) as u64
+        }
+    };
+    passwords[dial] += crossings;
+    while crossings > 0 && links.contains(&dial) && dial + 1 < dials.len() {
+        dial += 1;
+        let x = (crossings % DIAL_SIZE as u64) as i32;
+        crossings = crossings / DIAL_SIZE as u64 + turn(&mut dials[dial], x) as u64;
+        passwords[dial] += crossings;
+    }
+}
+
+fn lock(program: &[(usize, Instruction)], links: &[usize]) -> Vec<u64> {
+    let count = program.iter().map(|(dial, _)| dial + 1).max().unwrap_or(0);
+    if links.is_empty() {
+        return (0..count)
+            .map(|dial| {
+                part2(
+                    program
+                        .iter()
+                        .filter(|(d, _)| *d == dial)
+                        .map(|(_, instruction)| instruction),
+                )
+            })
+            .collect();
+    }
+
+    let mut dials = vec![START; count];
+    let mut passwords = vec![0; count];
+    for (dial, instruction) in program {
+        lock_step(&mut dials, &mut passwords, links, *dial, instruction);
+    }
+    passwords
 }
 
 fn main() {
+    let mut targets = Vec::new();
+    let mut histogram = false;
+    let mut lock_mode = false;
+    let mut links = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut list = || -> Vec<usize> {
+            args.next()
+                .unwrap_or_else(|| panic!("{arg} needs a comma separated list"))
+                .split(",")
+                .map(|t| t.parse().unwrap())
+                .collect()
+        };
+        match arg.as_str() {
+            "--targets" => targets = list(),
+            "--histogram" => histogram = true,
+            "--lock" => lock_mode = true,
+            "--link" => links = list(),
+            _ => panic!("unknown argument {arg}"),
+        }
+    }
+
     let input = std::fs::read_to_string("input/day01.txt").unwrap();
+
+    if lock_mode {
+        let input = parse_lock(&input);
+        let now = Instant::now();
+        let passwords = lock(&input, &links);
+        let time = now.elapsed();
+        for (dial, password) in passwords.iter().enumerate() {
+            println!("dial {dial}: {password}");
+        }
+        println!("lock: {} after {time:?}", passwords.iter().sum::<u64>());
+        return;
+    }
+
     let input = parse(&input);
 
     let now = Instant::now();
@@ -60,4 +375,23 @@ fn main() {
 
     println!("part1: {result1} after {time1:?}");
     println!("part2: {result2} after {time2:?}");
+
+    if !targets.is_empty() || histogram {
+        let wear = run(&input);
+        for target in targets {
+            assert!(
+                target < DIAL_SIZE as usize,
+                "target {target} is not on the dial"
+            );
+            println!(
+                "target {target}: {} landings, {} passes",
+                wear.landings[target], wear.passes[target]
+            );
+        }
+        if histogram {
+            for position in 0..DIAL_SIZE as usize {
+                println!("{position:>2}: {}", wear.visits(position));
+            }
+        }
+    }
 }
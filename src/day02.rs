@@ -1,57 +1,98 @@
-use std::time::Instant;
+use digit_dp::{DigitSum, Palindrome, Repeats};
+use std::{
+    env,
+    io::{self, BufWriter, Write},
+    time::Instant,
+};
 
 const _EXAMPLE: &str = r"11-22,95-115,998-1012,1188511880-1188511890,222220-222224,1698522-1698528,446443-446449,38593856-38593862,565653-565659,824824821-824824827,2121212118-2121212124";
 
-fn parse(input: &str) -> Vec<(u64, u64)> {
+type Range = (u128, u128);
+
+fn parse(input: &str, radix: u32) -> Vec<Range> {
     input
         .trim()
         .split(",")
         .map(|range| {
             let (left, right) = range.split_once("-").unwrap();
-            (left.parse().unwrap(), right.parse().unwrap())
+            let bound = |bound| {
+                u128::from_str_radix(bound, radix).unwrap_or_else(|err| {
+                    panic!("invalid bound {bound} in range {range}: {err} (ids are limited to 128 bits)")
+                })
+            };
+            (bound(left), bound(right))
         })
         .collect()
 }
 
-fn num_digits(x: u64) -> u32 {
-    x.ilog10() + 1
+fn merge_ranges(mut ranges: Vec<Range>) -> (Vec<Range>, Vec<[Range; 2]>) {
+    ranges.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let mut merged: Vec<Range> = Vec::new();
+    let mut overlaps = Vec::new();
+    let mut widest = (0, 0);
+    for range in ranges {
+        if let Some(merged_range) = merged.last_mut()
+            && range.0 <= merged_range.1.saturating_add(1)
+        {
+            if range.0 <= merged_range.1 {
+                overlaps.push([widest, range]);
+            }
+            if range.1 > merged_range.1 {
+                merged_range.1 = range.1;
+                widest = range;
+            }
+        } else {
+            merged.push(range);
+            widest = range;
+        }
+    }
+    (merged, overlaps)
+}
+
+fn num_digits(x: u128, radix: u32) -> u32 {
+    x.ilog(radix as u128) + 1
+}
+
+fn checked_add(sum: u128, x: u128) -> u128 {
+    sum.checked_add(x).expect("sum overflows u128")
 }
 
-fn has_pattern1(x: u64) -> bool {
-    let num_digits = num_digits(x);
+fn has_pattern1(x: u128, radix: u32) -> bool {
+    let num_digits = num_digits(x, radix);
     if num_digits % 2 == 1 {
         return false;
     }
 
-    let mid_factor = 10u64.pow(num_digits / 2);
+    let mid_factor = (radix as u128).pow(num_digits / 2);
     let (upper, lower) = (x / mid_factor, x % mid_factor);
 
     upper == lower
 }
 
-fn part1(input: &[(u64, u64)]) -> u64 {
+fn scan1(input: &[Range], radix: u32) -> u128 {
     input
         .iter()
-        .flat_map(|(low, high)| (*low..=*high).filter(|x| has_pattern1(*x)))
-        .sum()
+        .flat_map(|(low, high)| (*low.max(&1)..=*high).filter(|x| has_pattern1(*x, radix)))
+        .fold(0, checked_add)
 }
 
 struct Splitter {
-    value: u64,
-    split: u64,
+    value: u128,
+    split: u128,
 }
 
 impl Splitter {
-    fn new(value: u64, split_every: u32) -> Self {
+    fn new(value: u128, split_every: u32, radix: u32) -> Self {
         Self {
             value,
-            split: 10u64.pow(split_every),
+            split: (radix as u128).pow(split_every),
         }
     }
 }
 
 impl Iterator for Splitter {
-    type Item = u32;
+    type Item = u128;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.value == 0 {
@@ -59,42 +100,614 @@ impl Iterator for Splitter {
         }
         let (rest, split) = (self.value / self.split, self.value % self.split);
         self.value = rest;
-        Some(split as u32)
+        Some(split)
     }
 }
 
-fn has_pattern2(x: u64) -> bool {
-    let num_digits = num_digits(x);
+fn has_pattern2(x: u128, radix: u32) -> bool {
+    let num_digits = num_digits(x, radix);
     (1..=num_digits / 2).any(|split_every| {
         if !num_digits.is_multiple_of(split_every) {
             return false;
         }
 
-        let mut splitter = Splitter::new(x, split_every);
+        let mut splitter = Splitter::new(x, split_every, radix);
         let first = splitter.next().unwrap();
         splitter.all(|x| first == x)
     })
 }
 
-fn part2(input: &[(u64, u64)]) -> u64 {
+fn scan2(input: &[Range], radix: u32) -> u128 {
     input
         .iter()
-        .flat_map(|(low, high)| (*low..=*high).filter(|x| has_pattern2(*x)))
+        .flat_map(|(low, high)| (*low.max(&1)..=*high).filter(|x| has_pattern2(*x, radix)))
+        .fold(0, checked_add)
+}
+
+fn repunit(num_digits: u32, block: u32, radix: u32) -> u128 {
+    (0..num_digits / block)
+        .map(|i| (radix as u128).pow(i * block))
         .sum()
 }
 
+fn repeated(low: u128, high: u128, num_digits: u32, block: u32, radix: u32) -> (u128, u128) {
+    let repunit = repunit(num_digits, block, radix);
+    let first = low.div_ceil(repunit).max((radix as u128).pow(block - 1));
+    let last = (high / repunit).min((radix as u128).pow(block) - 1);
+    if first > last {
+        return (0, 0);
+    }
+    let count = last - first + 1;
+    let blocks = if count.is_multiple_of(2) {
+        (count / 2).checked_mul(first + last)
+    } else {
+        count.checked_mul((first + last) / 2)
+    };
+    let sum = blocks
+        .and_then(|blocks| blocks.checked_mul(repunit))
+        .expect("sum overflows u128");
+    (count, sum)
+}
+
+fn primitive(low: u128, high: u128, num_digits: u32, block: u32, radix: u32) -> (u128, u128) {
+    let (mut count, mut sum) = repeated(low, high, num_digits, block, radix);
+    for smaller in (1..block).filter(|smaller| block.is_multiple_of(*smaller)) {
+        let (c, s) = primitive(low, high, num_digits, smaller, radix);
+        count -= c;
+        sum -= s;
+    }
+    (count, sum)
+}
+
+fn part1(input: &[Range], radix: u32) -> u128 {
+    input
+        .iter()
+        .filter(|range| range.1 > 0)
+        .flat_map(|&(low, high)| {
+            let low = low.max(1);
+            (num_digits(low, radix)..=num_digits(high, radix))
+                .filter(|num_digits| num_digits % 2 == 0)
+                .map(move |num_digits| repeated(low, high, num_digits, num_digits / 2, radix).1)
+        })
+        .fold(0, checked_add)
+}
+
+fn exact_repeats(low: u128, high: u128, repeats: u32, radix: u32) -> (u128, u128) {
+    let low = low.max(1);
+    if low > high {
+        return (0, 0);
+    }
+    (num_digits(low, radix)..=num_digits(high, radix))
+        .filter(|num_digits| num_digits.is_multiple_of(repeats))
+        .map(|num_digits| primitive(low, high, num_digits, num_digits / repeats, radix))
+        .fold((0, 0), |(count, sum), (c, s)| {
+            (count + c, checked_add(sum, s))
+        })
+}
+
+fn part2(input: &[Range], radix: u32) -> u128 {
+    input
+        .iter()
+        .filter(|range| range.1 > 0)
+        .flat_map(|&(low, high)| {
+            (2..=num_digits(high, radix))
+                .map(move |repeats| exact_repeats(low, high, repeats, radix).1)
+        })
+        .fold(0, checked_add)
+}
+
+struct Matches {
+    next: Option<u128>,
+    high: u128,
+    radix: u32,
+}
+
+impl Matches {
+    fn new((low, high): Range, radix: u32) -> Self {
+        Self {
+            next: Some(low.max(1)),
+            high,
+            radix,
+        }
+    }
+}
+
+impl Iterator for Matches {
+    type Item = u128;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let radix = self.radix as u128;
+        loop {
+            let current = self.next.filter(|current| *current <= self.high)?;
+            let num_digits = num_digits(current, self.radix);
+            let candidate = (1..=num_digits / 2)
+                .filter(|block| num_digits.is_multiple_of(*block))
+                .filter_map(|block| {
+                    let repunit = repunit(num_digits, block, self.radix);
+                    let first = current.div_ceil(repunit).max(radix.pow(block - 1));
+                    (first < radix.pow(block))
+                        .then(|| first.checked_mul(repunit))
+                        .flatten()
+                })
+                .min();
+            match candidate {
+                Some(x) if x <= self.high => {
+                    self.next = x.checked_add(1);
+                    return Some(x);
+                }
+                Some(_) => self.next = None,
+                None => self.next = radix.checked_pow(num_digits),
+            }
+        }
+    }
+}
+
+fn primitive_block(x: u128, radix: u32) -> (u128, u32) {
+    let num_digits = num_digits(x, radix);
+    (1..=num_digits)
+        .filter(|block| num_digits.is_multiple_of(*block))
+        .find_map(|block| {
+            let head = x / (radix as u128).pow(num_digits - block);
+            (head.checked_mul(repunit(num_digits, block, radix)) == Some(x))
+                .then_some((head, num_digits / block))
+        })
+        .unwrap()
+}
+
+fn explain(input: &[(Range, Vec<Range>)], radix: u32, out: &mut impl Write) -> io::Result<()> {
+    for (range, original) in input {
+        for id in Matches::new(*range, radix) {
+            let sources: Vec<_> = original
+                .iter()
+                .filter(|range| range.0 <= id && id <= range.1)
+                .map(|range| format!("{}-{}", to_radix(range.0, radix), to_radix(range.1, radix)))
+                .collect();
+            let (block, repeats) = primitive_block(id, radix);
+            let parts = if repeats.is_multiple_of(2) {
+                "both"
+            } else {
+                "part2"
+            };
+            writeln!(
+                out,
+                "{} in {}: block {} x{repeats}, {parts}",
+                to_radix(id, radix),
+                sources.join(", "),
+                to_radix(block, radix)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn mobius(mut n: u32) -> i32 {
+    let mut mobius = 1;
+    let mut factor = 2;
+    while factor * factor <= n {
+        if n.is_multiple_of(factor) {
+            n /= factor;
+            if n.is_multiple_of(factor) {
+                return 0;
+            }
+            mobius = -mobius;
+        }
+        factor += 1;
+    }
+    if n > 1 { -mobius } else { mobius }
+}
+
+fn dp1(input: &[Range], radix: u32) -> u128 {
+    input
+        .iter()
+        .map(|range| digit_dp::count_sum(&Repeats(2), *range, radix).1)
+        .fold(0, checked_add)
+}
+
+fn dp2(input: &[Range], radix: u32) -> u128 {
+    let accumulate = |(high, low): (u128, u128), sum| {
+        let (low, carry) = low.overflowing_add(sum);
+        (high + u128::from(carry), low)
+    };
+    let (mut added, mut removed) = ((0, 0), (0, 0));
+    for &range in input.iter().filter(|range| range.1 > 0) {
+        for repeats in 2..=num_digits(range.1, radix) {
+            let (_, sum) = digit_dp::count_sum(&Repeats(repeats), range, radix);
+            match mobius(repeats) {
+                -1 => added = accumulate(added, sum),
+                1 => removed = accumulate(removed, sum),
+                _ => {}
+            }
+        }
+    }
+    let (low, borrow) = added.1.overflowing_sub(removed.1);
+    assert_eq!(
+        added.0 - removed.0 - u128::from(borrow),
+        0,
+        "sum overflows u128"
+    );
+    low
+}
+
+fn to_radix(mut x: u128, radix: u32) -> String {
+    let mut digits = Vec::new();
+    loop {
+        digits.push(char::from_digit((x % radix as u128) as u32, radix).unwrap());
+        x /= radix as u128;
+        if x == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
 fn main() {
+    let mut radix = 10;
+    let mut scan = false;
+    let mut breakdown = false;
+    let mut allow_double_count = false;
+    let mut explain_ids = false;
+    let mut digit_dp = false;
+    let mut digit_sum = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--radix" => radix = args.next().and_then(|r| r.parse().ok()).unwrap(),
+            "--scan" => scan = true,
+            "--breakdown" => breakdown = true,
+            "--allow-double-count" => allow_double_count = true,
+            "--explain" => explain_ids = true,
+            "--digit-dp" => digit_dp = true,
+            "--digit-sum" => digit_sum = args.next().and_then(|s| s.parse().ok()),
+            _ => panic!("unknown argument {arg}"),
+        }
+    }
+    assert!((2..=36).contains(&radix), "radix must be between 2 and 36");
+
     let input = std::fs::read_to_string("input/day02.txt").unwrap();
-    let input = parse(&input);
+    let ranges = parse(&input, radix);
+    let mut input = ranges.clone();
+    let mut sources: Vec<_> = input.iter().map(|range| vec![*range]).collect();
+    if !allow_double_count {
+        let (merged, overlaps) = merge_ranges(ranges.clone());
+        for [a, b] in overlaps {
+            eprintln!(
+                "warning: ranges {}-{} and {}-{} overlap",
+                to_radix(a.0, radix),
+                to_radix(a.1, radix),
+                to_radix(b.0, radix),
+                to_radix(b.1, radix)
+            );
+        }
+        sources = merged
+            .iter()
+            .map(|merged| {
+                ranges
+                    .iter()
+                    .filter(|range| merged.0 <= range.0 && range.1 <= merged.1)
+                    .copied()
+                    .collect()
+            })
+            .collect();
+        input = merged;
+    }
 
     let now = Instant::now();
-    let result1 = part1(&input);
+    let result1 = part1(&input, radix);
     let time1 = now.elapsed();
 
     let now = Instant::now();
-    let result2 = part2(&input);
+    let result2 = part2(&input, radix);
     let time2 = now.elapsed();
 
     println!("part1: {result1} after {time1:?}");
     println!("part2: {result2} after {time2:?}");
+
+    if scan {
+        let now = Instant::now();
+        let scanned1 = scan1(&input, radix);
+        let time1 = now.elapsed();
+
+        let now = Instant::now();
+        let scanned2 = scan2(&input, radix);
+        let time2 = now.elapsed();
+
+        println!("scan1: {scanned1} after {time1:?}");
+        println!("scan2: {scanned2} after {time2:?}");
+    }
+
+    if digit_dp {
+        let now = Instant::now();
+        let counted1 = dp1(&input, radix);
+        let time1 = now.elapsed();
+
+        let now = Instant::now();
+        let counted2 = dp2(&input, radix);
+        let time2 = now.elapsed();
+
+        println!("digit-dp part1: {counted1} after {time1:?}");
+        println!("digit-dp part2: {counted2} after {time2:?}");
+
+        let (count, sum) = input
+            .iter()
+            .map(|range| digit_dp::count_sum(&Palindrome, *range, radix))
+            .fold((0, 0), |(count, sum), (c, s)| {
+                (count + c, checked_add(sum, s))
+            });
+        println!("palindromes: {count} ids, sum {sum}");
+    }
+
+    if let Some(target) = digit_sum {
+        let (count, sum) = input
+            .iter()
+            .map(|range| digit_dp::count_sum(&DigitSum(target), *range, radix))
+            .fold((0, 0), |(count, sum), (c, s)| {
+                (count + c, checked_add(sum, s))
+            });
+        println!("digit sum {target}: {count} ids, sum {sum}");
+    }
+
+    if breakdown {
+        for &(low, high) in &ranges {
+            println!("{}-{}:", to_radix(low, radix), to_radix(high, radix));
+            for repeats in 2..=num_digits(high.max(1), radix) {
+                let (count, sum) = exact_repeats(low, high, repeats, radix);
+                if count > 0 {
+                    println!("  {repeats} repeats: {count} ids, sum {sum}");
+                }
+            }
+        }
+    }
+
+    if explain_ids {
+        let input: Vec<_> = input.into_iter().zip(sources).collect();
+        let _ = explain(&input, radix, &mut BufWriter::new(io::stdout().lock()));
+    }
+}
+
+mod digit_dp {
+    use super::checked_add;
+    use std::{collections::HashMap, hash::Hash};
+
+    pub trait Predicate {
+        type State: Clone + Eq + Hash;
+
+        fn start(&self, num_digits: u32) -> Option<Self::State>;
+
+        fn tie(&self, _num_digits: u32, _position: u32) -> Option<u32> {
+            None
+        }
+
+        fn step(&self, state: &Self::State, digit: u32, copies: u32) -> Option<Self::State>;
+
+        fn accept(&self, state: &Self::State) -> bool;
+    }
+
+    pub struct Repeats(pub u32);
+
+    impl Predicate for Repeats {
+        type State = ();
+
+        fn start(&self, num_digits: u32) -> Option<Self::State> {
+            num_digits.is_multiple_of(self.0).then_some(())
+        }
+
+        fn tie(&self, num_digits: u32, position: u32) -> Option<u32> {
+            let block = num_digits / self.0;
+            (position >= block).then(|| position - block)
+        }
+
+        fn step(&self, _: &Self::State, _: u32, _: u32) -> Option<Self::State> {
+            Some(())
+        }
+
+        fn accept(&self, _: &Self::State) -> bool {
+            true
+        }
+    }
+
+    pub struct Palindrome;
+
+    impl Predicate for Palindrome {
+        type State = ();
+
+        fn start(&self, _: u32) -> Option<Self::State> {
+            Some(())
+        }
+
+        fn tie(&self, num_digits: u32, position: u32) -> Option<u32> {
+            let mirror = num_digits - 1 - position;
+            (mirror < position).then_some(mirror)
+        }
+
+        fn step(&self, _: &Self::State, _: u32, _: u32) -> Option<Self::State> {
+            Some(())
+        }
+
+        fn accept(&self, _: &Self::State) -> bool {
+            true
+        }
+    }
+
+    pub struct DigitSum(pub u32);
+
+    impl Predicate for DigitSum {
+        type State = u32;
+
+        fn start(&self, _: u32) -> Option<Self::State> {
+            Some(0)
+        }
+
+        fn step(&self, sum: &Self::State, digit: u32, copies: u32) -> Option<Self::State> {
+            Some(sum + digit * copies).filter(|sum| *sum <= self.0)
+        }
+
+        fn accept(&self, sum: &Self::State) -> bool {
+            *sum == self.0
+        }
+    }
+
+    struct Class {
+        positions: Vec<u32>,
+        weight: u128,
+    }
+
+    type Diff = Option<(u32, bool)>;
+    type Key<S> = (usize, S, Diff, Diff);
+
+    struct Solver<'a, P: Predicate> {
+        predicate: &'a P,
+        classes: Vec<Class>,
+        lower: Option<Vec<u32>>,
+        upper: Option<Vec<u32>>,
+        radix: u32,
+        memo: HashMap<Key<P::State>, (u128, u128)>,
+    }
+
+    impl<P: Predicate> Solver<'_, P> {
+        fn solve(&mut self, key: Key<P::State>) -> (u128, u128) {
+            let (class, state, lower_diff, upper_diff) = key.clone();
+            if class == self.classes.len() {
+                let within = lower_diff.is_none_or(|(_, less)| !less)
+                    && upper_diff.is_none_or(|(_, less)| less);
+                return (u128::from(within && self.predicate.accept(&state)), 0);
+            }
+            if let Some(result) = self.memo.get(&key) {
+                return *result;
+            }
+
+            let (mut count, mut sum) = (0, 0);
+            let copies = self.classes[class].positions.len() as u32;
+            for digit in u32::from(class == 0)..self.radix {
+                let Some(next) = self.predicate.step(&state, digit, copies) else {
+                    continue;
+                };
+                let positions = &self.classes[class].positions;
+                let lower = first_diff(&self.lower, positions, digit, lower_diff);
+                let upper = first_diff(&self.upper, positions, digit, upper_diff);
+                let (c, s) = self.solve((class + 1, next, lower, upper));
+                if c == 0 {
+                    continue;
+                }
+                let value = (digit as u128)
+                    .checked_mul(self.classes[class].weight)
+                    .and_then(|value| value.checked_mul(c))
+                    .expect("sum overflows u128");
+                count += c;
+                sum = checked_add(checked_add(sum, s), value);
+            }
+            self.memo.insert(key, (count, sum));
+            (count, sum)
+        }
+    }
+
+    fn first_diff(bound: &Option<Vec<u32>>, positions: &[u32], digit: u32, mut diff: Diff) -> Diff {
+        if let Some(bound) = bound {
+            for &position in positions {
+                let expected = bound[position as usize];
+                if digit != expected && diff.is_none_or(|(p, _)| position < p) {
+                    diff = Some((position, digit < expected));
+                }
+            }
+        }
+        diff
+    }
+
+    fn solve(
+        predicate: &impl Predicate,
+        num_digits: u32,
+        (lower, upper): (Option<Vec<u32>>, Option<Vec<u32>>),
+        radix: u32,
+    ) -> (u128, u128) {
+        let Some(state) = predicate.start(num_digits) else {
+            return (0, 0);
+        };
+
+        let mut root: Vec<u32> = (0..num_digits).collect();
+        let mut classes: Vec<Class> = Vec::new();
+        for position in 0..num_digits {
+            if let Some(tied) = predicate.tie(num_digits, position) {
+                assert!(tied < position, "digits can only be tied to earlier digits");
+                root[position as usize] = root[tied as usize];
+            }
+        }
+        let mut index = vec![0; num_digits as usize];
+        for position in 0..num_digits {
+            let weight = (radix as u128).pow(num_digits - 1 - position);
+            if root[position as usize] == position {
+                index[position as usize] = classes.len();
+                classes.push(Class {
+                    positions: vec![position],
+                    weight,
+                });
+            } else {
+                let class = &mut classes[index[root[position as usize] as usize]];
+                class.positions.push(position);
+                class.weight += weight;
+            }
+        }
+
+        Solver {
+            predicate,
+            classes,
+            lower,
+            upper,
+            radix,
+            memo: HashMap::new(),
+        }
+        .solve((0, state, None, None))
+    }
+
+    fn digits(mut x: u128, radix: u32) -> Vec<u32> {
+        let mut digits = Vec::new();
+        while x > 0 {
+            digits.push((x % radix as u128) as u32);
+            x /= radix as u128;
+        }
+        digits.reverse();
+        digits
+    }
+
+    pub fn count_sum(
+        predicate: &impl Predicate,
+        (low, high): (u128, u128),
+        radix: u32,
+    ) -> (u128, u128) {
+        let low = low.max(1);
+        if low > high {
+            return (0, 0);
+        }
+        let (low, high) = (digits(low, radix), digits(high, radix));
+        let (mut count, mut sum) = (0, 0);
+        for num_digits in low.len()..=high.len() {
+            let lower = (num_digits == low.len()).then(|| low.clone());
+            let upper = (num_digits == high.len()).then(|| high.clone());
+            let (c, s) = solve(predicate, num_digits as u32, (lower, upper), radix);
+            count += c;
+            sum = checked_add(sum, s);
+        }
+        (count, sum)
+    }
+}
+
+#[test]
+fn test_digit_dp_near_max() {
+    for (input, radix) in [
+        (
+            "ffffffffffffffffffffffffffffff00-ffffffffffffffffffffffffffffffff",
+            16,
+        ),
+        (
+            "340282366920938463463374607431768211000-340282366920938463463374607431768211455",
+            10,
+        ),
+        (
+            "fffffffffffffffeffffffffffffffff-fffffffffffffffffffffffffffffff0",
+            16,
+        ),
+    ] {
+        let input = parse(input, radix);
+        assert_eq!(dp1(&input, radix), part1(&input, radix));
+        assert_eq!(dp2(&input, radix), part2(&input, radix));
+    }
 }
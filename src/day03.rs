@@ -1,4 +1,12 @@
-use std::{array, time::Instant};
+use std::{
+    collections::VecDeque,
+    env,
+    fmt::Display,
+    io::{self, IsTerminal},
+    iter::Sum,
+    ops::Add,
+    time::Instant,
+};
 
 const _EXAMPLE: &str = r"987654321111111
 811111111111119
@@ -6,83 +14,524 @@ const _EXAMPLE: &str = r"987654321111111
 818181911112111
 ";
 
-fn parse(input: &str) -> Vec<Vec<u8>> {
-    input
+fn parse(input: &str, radix: u32) -> Result<Vec<Vec<u8>>, String> {
+    let mut errors = Vec::new();
+    let banks = input
         .trim()
         .lines()
-        .map(|line| {
+        .enumerate()
+        .map(|(bank, line)| {
             line.chars()
-                .map(|x| x.to_digit(10).unwrap() as u8)
+                .enumerate()
+                .map(|(column, x)| {
+                    x.to_digit(radix).unwrap_or_else(|| {
+                        errors.push(format!(
+                            "invalid battery rating {x:?} in bank {} column {}",
+                            bank + 1,
+                            column + 1
+                        ));
+                        0
+                    }) as u8
+                })
                 .collect()
         })
-        .collect()
+        .collect();
+    if errors.is_empty() {
+        Ok(banks)
+    } else {
+        Err(errors.join("\n"))
+    }
 }
 
-fn part1(banks: &[Vec<u8>]) -> u32 {
-    banks
-        .iter()
-        .map(|batteries| {
-            let length = batteries.len();
-            batteries.iter().enumerate().fold(
-                (u8::MIN, u8::MIN),
-                |(max_hi, max_lo), (idx, &battery)| {
-                    if idx != length - 1 && battery > max_hi {
-                        (battery, u8::MIN)
-                    } else if battery > max_lo {
-                        (max_hi, battery)
-                    } else {
-                        (max_hi, max_lo)
-                    }
-                },
-            )
-        })
-        .map(|(hi, lo)| hi as u32 * 10 + lo as u32)
-        .sum()
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Joltage {
+    digits: Vec<u8>,
+    radix: u32,
 }
 
-fn part2(banks: &[Vec<u8>]) -> u64 {
-    banks
-        .iter()
-        .map(|batteries| {
-            let length = batteries.len();
-            batteries.iter().enumerate().fold(
-                array::from_fn::<_, 12, _>(|_| u8::MIN),
-                |mut max, (idx, &battery)| {
-                    let remaining = length - idx;
-                    let start = max.len().saturating_sub(remaining);
-                    for i in start..max.len() {
-                        if battery > max[i] {
-                            max[i] = battery;
-                            for m in &mut max[i + 1..] {
-                                *m = u8::MIN;
-                            }
-                            break;
-                        }
-                    }
-                    max
-                },
-            )
+impl Add for Joltage {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        assert_eq!(
+            self.radix, rhs.radix,
+            "cannot add joltages of different radix"
+        );
+        let radix = self.radix as u8;
+        let (long, short) = if self.digits.len() >= rhs.digits.len() {
+            (self.digits, rhs.digits)
+        } else {
+            (rhs.digits, self.digits)
+        };
+        let mut digits = Vec::with_capacity(long.len() + 1);
+        let mut carry = 0;
+        for (i, x) in long.iter().rev().enumerate() {
+            let y = short.len().checked_sub(i + 1).map_or(0, |j| short[j]);
+            let digit = x + y + carry;
+            digits.push(digit % radix);
+            carry = digit / radix;
+        }
+        if carry > 0 {
+            digits.push(carry);
+        }
+        digits.reverse();
+        Self {
+            digits,
+            radix: self.radix,
+        }
+    }
+}
+
+impl Sum for Joltage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.reduce(Add::add).unwrap_or(Joltage {
+            digits: vec![0],
+            radix: 10,
         })
-        .map(|max| {
-            max.iter().enumerate().fold(0, |num, (idx, x)| {
-                num + 10u64.pow((max.len() - 1 - idx) as u32) * *x as u64
-            })
+    }
+}
+
+impl Display for Joltage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(start) = self.digits.iter().position(|d| *d != 0) else {
+            return write!(f, "0");
+        };
+        for digit in &self.digits[start..] {
+            write!(
+                f,
+                "{}",
+                char::from_digit(*digit as u32, self.radix).unwrap()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Selection {
+    positions: Vec<usize>,
+    joltage: Joltage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    Max,
+    Min,
+    NonAdjacent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ties {
+    Earliest,
+    Latest,
+}
+
+fn monotonic(batteries: &[u8], k: usize, pop: impl Fn(u8, u8) -> bool) -> Vec<usize> {
+    let mut stack = Vec::with_capacity(k);
+    for (idx, &battery) in batteries.iter().enumerate() {
+        let remaining = batteries.len() - idx;
+        while let Some(&top) = stack.last()
+            && pop(batteries[top], battery)
+            && stack.len() + remaining > k
+        {
+            stack.pop();
+        }
+        if stack.len() < k {
+            stack.push(idx);
+        }
+    }
+    stack
+}
+
+fn non_adjacent(batteries: &[u8], k: usize) -> Vec<usize> {
+    assert!(
+        2 * k <= batteries.len() + 1,
+        "cannot pick {k} non-adjacent of {} batteries",
+        batteries.len()
+    );
+    let mut positions = Vec::with_capacity(k);
+    let mut window = VecDeque::new();
+    let mut start = 0;
+    let mut end = 0;
+    for left in (1..=k).rev() {
+        while end + 2 * left <= batteries.len() + 1 {
+            while let Some(&back) = window.back()
+                && batteries[back] < batteries[end]
+            {
+                window.pop_back();
+            }
+            window.push_back(end);
+            end += 1;
+        }
+        while let Some(&front) = window.front()
+            && front < start
+        {
+            window.pop_front();
+        }
+        let chosen = window.pop_front().unwrap();
+        positions.push(chosen);
+        start = chosen + 2;
+    }
+    positions
+}
+
+fn select(batteries: &[u8], k: usize, strategy: Strategy, radix: u32) -> Selection {
+    assert!(
+        k <= batteries.len(),
+        "cannot pick {k} of {} batteries",
+        batteries.len()
+    );
+    let positions = match strategy {
+        Strategy::Max => monotonic(batteries, k, |top, battery| top < battery),
+        Strategy::Min if k == 0 => Vec::new(),
+        Strategy::Min => {
+            let first = (0..=batteries.len() - k)
+                .filter(|&idx| batteries[idx] != 0)
+                .min_by_key(|&idx| batteries[idx])
+                .expect("no battery without a leading zero");
+            let rest = &batteries[first + 1..];
+            let mut positions = vec![first];
+            positions.extend(
+                monotonic(rest, k - 1, |top, battery| top > battery)
+                    .into_iter()
+                    .map(|idx| idx + first + 1),
+            );
+            positions
+        }
+        Strategy::NonAdjacent => non_adjacent(batteries, k),
+    };
+    Selection {
+        joltage: Joltage {
+            digits: positions.iter().map(|&idx| batteries[idx]).collect(),
+            radix,
+        },
+        positions,
+    }
+}
+
+fn latest_ties(batteries: &[u8], positions: &[usize], strategy: Strategy) -> Vec<usize> {
+    let gap = if strategy == Strategy::NonAdjacent {
+        2
+    } else {
+        1
+    };
+    let mut latest = Vec::with_capacity(positions.len());
+    let mut end = batteries.len() + gap - 1;
+    for &position in positions.iter().rev() {
+        let idx = (0..end + 1 - gap)
+            .rev()
+            .find(|&idx| batteries[idx] == batteries[position])
+            .unwrap();
+        latest.push(idx);
+        end = idx;
+    }
+    latest.reverse();
+    latest
+}
+
+fn removal_order(batteries: &[u8]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(batteries.len());
+    let mut stack: Vec<usize> = Vec::with_capacity(batteries.len());
+    for (idx, &battery) in batteries.iter().enumerate() {
+        while let Some(&top) = stack.last()
+            && batteries[top] < battery
+        {
+            order.push(stack.pop().unwrap());
+        }
+        stack.push(idx);
+    }
+    order.extend(stack.into_iter().rev());
+    order
+}
+
+fn joltage_table(batteries: &[u8], radix: u32) -> Vec<Joltage> {
+    let mut rank = vec![0; batteries.len()];
+    for (r, idx) in removal_order(batteries).into_iter().enumerate() {
+        rank[idx] = r;
+    }
+    (1..=batteries.len())
+        .map(|k| Joltage {
+            digits: batteries
+                .iter()
+                .zip(&rank)
+                .filter(|(_, r)| **r >= batteries.len() - k)
+                .map(|(battery, _)| *battery)
+                .collect(),
+            radix,
         })
-        .sum()
+        .collect()
+}
+
+fn render(batteries: &[u8], positions: &[usize], color: bool, radix: u32) -> String {
+    let mut chosen = positions.iter().peekable();
+    let mut rendered = String::new();
+    for (idx, battery) in batteries.iter().enumerate() {
+        let battery = char::from_digit(*battery as u32, radix).unwrap();
+        if chosen.next_if_eq(&&idx).is_some() {
+            if color {
+                rendered += &format!("\x1b[1;32m{battery}\x1b[0m");
+            } else {
+                rendered += &format!("[{battery}]");
+            }
+        } else {
+            rendered.push(battery);
+        }
+    }
+    rendered
+}
+
+fn check(batteries: &[u8], k: usize, strategy: Strategy) -> Result<(), String> {
+    let needed = match strategy {
+        Strategy::Max | Strategy::Min => k,
+        Strategy::NonAdjacent => (2 * k).saturating_sub(1),
+    };
+    if batteries.len() < needed {
+        return Err(format!(
+            "cannot pick {k} of {} batteries with strategy {strategy:?}",
+            batteries.len()
+        ));
+    }
+    if strategy == Strategy::Min
+        && k > 0
+        && batteries[..=batteries.len() - k].iter().all(|b| *b == 0)
+    {
+        return Err(format!("cannot pick {k} batteries without a leading zero"));
+    }
+    Ok(())
+}
+
+fn total_joltage(
+    banks: &[Vec<u8>],
+    k: usize,
+    strategy: Strategy,
+    radix: u32,
+) -> Result<Joltage, String> {
+    for (bank, batteries) in banks.iter().enumerate() {
+        check(batteries, k, strategy).map_err(|err| format!("bank {}: {err}", bank + 1))?;
+    }
+    Ok(banks
+        .iter()
+        .map(|batteries| select(batteries, k, strategy, radix).joltage)
+        .sum())
+}
+
+fn part1(banks: &[Vec<u8>], radix: u32) -> Result<Joltage, String> {
+    total_joltage(banks, 2, Strategy::Max, radix)
+}
+
+fn part2(banks: &[Vec<u8>], radix: u32) -> Result<Joltage, String> {
+    total_joltage(banks, 12, Strategy::Max, radix)
 }
 
 fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let radix = args
+        .iter()
+        .position(|arg| arg == "--radix")
+        .map_or(10, |i| args[i + 1].parse().expect("--radix needs a number"));
+    assert!((2..=36).contains(&radix), "radix must be between 2 and 36");
+
     let input = std::fs::read_to_string("input/day03.txt").unwrap();
-    let input = parse(&input);
+    let input = parse(&input, radix).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1)
+    });
 
     let now = Instant::now();
-    let result1 = part1(&input);
+    let result1 = part1(&input, radix);
     let time1 = now.elapsed();
 
     let now = Instant::now();
-    let result2 = part2(&input);
+    let result2 = part2(&input, radix);
     let time2 = now.elapsed();
 
-    println!("part1: {result1} after {time1:?}");
-    println!("part2: {result2} after {time2:?}");
+    match result1 {
+        Ok(result1) => println!("part1: {result1} after {time1:?}"),
+        Err(err) => eprintln!("part1: {err}"),
+    }
+    match result2 {
+        Ok(result2) => println!("part2: {result2} after {time2:?}"),
+        Err(err) => eprintln!("part2: {err}"),
+    }
+
+    let mut strategy = Strategy::Max;
+    let mut ties = Ties::Earliest;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--radix" => {
+                args.next();
+            }
+            "--strategy" => {
+                strategy = match args.next().as_deref() {
+                    Some("max") => Strategy::Max,
+                    Some("min") => Strategy::Min,
+                    Some("non-adjacent") => Strategy::NonAdjacent,
+                    other => panic!("unknown strategy {other:?}"),
+                }
+            }
+            "--ties" => {
+                ties = match args.next().as_deref() {
+                    Some("earliest") => Ties::Earliest,
+                    Some("latest") => Ties::Latest,
+                    other => panic!("unknown tie rule {other:?}"),
+                }
+            }
+            "-k" => {
+                let k = args
+                    .next()
+                    .and_then(|k| k.parse().ok())
+                    .expect("-k needs a number");
+                let now = Instant::now();
+                let result = total_joltage(&input, k, strategy, radix);
+                let time = now.elapsed();
+                match result {
+                    Ok(result) => println!("k={k}: {result} after {time:?}"),
+                    Err(err) => eprintln!("k={k}: {err}"),
+                }
+            }
+            "--show" => {
+                let k = args
+                    .next()
+                    .and_then(|k| k.parse().ok())
+                    .expect("--show needs a number");
+                let color = io::stdout().is_terminal();
+                for (bank, batteries) in input.iter().enumerate() {
+                    if let Err(err) = check(batteries, k, strategy) {
+                        eprintln!("bank {}: {err}", bank + 1);
+                        continue;
+                    }
+                    let mut selection = select(batteries, k, strategy, radix);
+                    if ties == Ties::Latest {
+                        selection.positions =
+                            latest_ties(batteries, &selection.positions, strategy);
+                    }
+                    println!(
+                        "{} {} {:?}",
+                        render(batteries, &selection.positions, color, radix),
+                        selection.joltage,
+                        selection.positions
+                    );
+                }
+            }
+            "--table" => {
+                let tables: Vec<_> = input.iter().map(|b| joltage_table(b, radix)).collect();
+                println!("bank,k,joltage");
+                for (bank, table) in tables.iter().enumerate() {
+                    for (k, joltage) in table.iter().enumerate() {
+                        println!("{},{},{joltage}", bank + 1, k + 1);
+                    }
+                }
+                let shortest = tables.iter().map(Vec::len).min().unwrap_or(0);
+                for k in 0..shortest {
+                    let total: Joltage = tables.iter().map(|table| table[k].clone()).sum();
+                    println!("total,{},{total}", k + 1);
+                }
+            }
+            _ => panic!("unknown argument {arg}"),
+        }
+    }
+}
+
+#[test]
+fn test_max() {
+    let banks = parse(_EXAMPLE, 10).unwrap();
+    assert_eq!(part1(&banks, 10).unwrap().to_string(), "357");
+    assert_eq!(part2(&banks, 10).unwrap().to_string(), "3121910778619");
+    assert!(part2(&[vec![1, 2, 3, 4]], 10).is_err());
+    assert_eq!(
+        select(&[1, 2, 3], 0, Strategy::Max, 10).joltage.to_string(),
+        "0"
+    );
+    assert_eq!(select(&banks[1], 2, Strategy::Max, 10).positions, [0, 14]);
+    assert_eq!(select(&[9, 9, 9], 2, Strategy::Max, 10).positions, [0, 1]);
+}
+
+#[test]
+fn test_min() {
+    let banks = parse(_EXAMPLE, 10).unwrap();
+    let min: Vec<_> = banks
+        .iter()
+        .map(|batteries| select(batteries, 2, Strategy::Min, 10).joltage.to_string())
+        .collect();
+    assert_eq!(min, ["11", "11", "22", "11"]);
+    assert_eq!(
+        select(&banks[2], 4, Strategy::Min, 10).joltage.to_string(),
+        "2222"
+    );
+}
+
+#[test]
+fn test_non_adjacent() {
+    let banks = parse(_EXAMPLE, 10).unwrap();
+    let max: Vec<_> = banks
+        .iter()
+        .map(|batteries| {
+            select(batteries, 2, Strategy::NonAdjacent, 10)
+                .joltage
+                .to_string()
+        })
+        .collect();
+    assert_eq!(max, ["97", "89", "48", "92"]);
+    assert_eq!(
+        select(&banks[0], 8, Strategy::NonAdjacent, 10).positions,
+        [0, 2, 4, 6, 8, 10, 12, 14]
+    );
+    assert_eq!(
+        select(&[9, 9, 1, 9], 2, Strategy::NonAdjacent, 10).positions,
+        [0, 3]
+    );
+}
+
+#[test]
+fn test_ties() {
+    fn brute(batteries: &[u8], k: usize, strategy: Strategy, ties: Ties) -> Vec<usize> {
+        (0u32..1 << batteries.len())
+            .filter(|mask| mask.count_ones() as usize == k)
+            .map(|mask| {
+                (0..batteries.len())
+                    .filter(|i| mask >> i & 1 == 1)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|positions| {
+                strategy != Strategy::NonAdjacent || positions.windows(2).all(|w| w[1] > w[0] + 1)
+            })
+            .filter(|positions| strategy != Strategy::Min || k == 0 || batteries[positions[0]] != 0)
+            .min_by_key(|positions| {
+                let digits: Vec<_> = positions.iter().map(|&i| batteries[i]).collect();
+                let key = match strategy {
+                    Strategy::Min => digits,
+                    _ => digits.iter().map(|d| 9 - d).collect(),
+                };
+                let order = match ties {
+                    Ties::Earliest => positions.iter().map(|&i| i as isize).collect::<Vec<_>>(),
+                    Ties::Latest => positions.iter().map(|&i| -(i as isize)).collect(),
+                };
+                (key, order)
+            })
+            .unwrap()
+    }
+
+    for batteries in [
+        &[8, 1, 1, 1, 8, 1, 1, 9][..],
+        &[2, 2, 1, 2, 2, 1, 2],
+        &[5, 5, 5, 5, 5, 5],
+        &[3, 9, 3, 9, 3, 9, 3],
+        &[0, 3, 0, 1, 2, 0, 2],
+    ] {
+        for k in 1..=batteries.len().div_ceil(2) {
+            for strategy in [Strategy::Max, Strategy::Min, Strategy::NonAdjacent] {
+                let positions = select(batteries, k, strategy, 10).positions;
+                assert_eq!(
+                    positions,
+                    brute(batteries, k, strategy, Ties::Earliest),
+                    "{batteries:?} k={k} {strategy:?}"
+                );
+                assert_eq!(
+                    latest_ties(batteries, &positions, strategy),
+                    brute(batteries, k, strategy, Ties::Latest),
+                    "{batteries:?} k={k} {strategy:?} latest"
+                );
+            }
+        }
+    }
 }
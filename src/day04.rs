@@ -1,4 +1,12 @@
-use std::time::Instant;
+use bitgrid::BitGrid;
+use lattice::Lattice;
+use std::{
+    env, fs,
+    io::{IsTerminal, stdout},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
 
 const _EXAMPLE: &str = r"..@@.@@@@.
 @@@.@.@.@@
@@ -12,95 +20,931 @@ const _EXAMPLE: &str = r"..@@.@@@@.
 @.@.@@@.@.
 ";
 
-fn parse(input: &str) -> Vec<Vec<bool>> {
-    input
-        .trim()
-        .lines()
-        .map(|line| line.chars().map(|x| x == '@').collect())
-        .collect()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Neighbourhood {
+    VonNeumann,
+    Moore,
+    Chebyshev(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    LessThan,
+    AtMost,
 }
 
-fn neighbors(rolls: &[Vec<bool>], (y, x): (usize, usize)) -> Vec<bool> {
-    let mut neighbors = Vec::new();
-    if x > 0 {
-        neighbors.push(rolls[y][x - 1]);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    Walls,
+    WrapHorizontal,
+    WrapVertical,
+    Torus,
+}
+
+impl Boundary {
+    fn wraps_horizontally(self) -> bool {
+        matches!(self, Boundary::WrapHorizontal | Boundary::Torus)
     }
-    if x < rolls[0].len() - 1 {
-        neighbors.push(rolls[y][x + 1]);
+
+    fn wraps_vertically(self) -> bool {
+        matches!(self, Boundary::WrapVertical | Boundary::Torus)
     }
-    if y > 0 {
-        neighbors.push(rolls[y - 1][x]);
-        if x > 0 {
-            neighbors.push(rolls[y - 1][x - 1]);
-        }
-        if x < rolls[0].len() - 1 {
-            neighbors.push(rolls[y - 1][x + 1]);
+}
+
+#[derive(Debug, Clone)]
+struct Rules {
+    neighbourhood: Neighbourhood,
+    threshold: usize,
+    comparison: Comparison,
+    boundary: Boundary,
+}
+
+impl Rules {
+    fn new(
+        neighbourhood: Neighbourhood,
+        threshold: usize,
+        comparison: Comparison,
+        boundary: Boundary,
+    ) -> Self {
+        Self {
+            neighbourhood,
+            threshold,
+            comparison,
+            boundary,
         }
     }
-    if y < rolls.len() - 1 {
-        neighbors.push(rolls[y + 1][x]);
-        if x > 0 {
-            neighbors.push(rolls[y + 1][x - 1]);
-        }
-        if x < rolls[0].len() - 1 {
-            neighbors.push(rolls[y + 1][x + 1]);
+
+    fn accessible(&self, occupied: usize) -> bool {
+        match self.comparison {
+            Comparison::LessThan => occupied < self.threshold,
+            Comparison::AtMost => occupied <= self.threshold,
         }
     }
-    neighbors
 }
 
-fn part1(rolls: &[Vec<bool>]) -> u32 {
-    let mut accessible = 0;
-    for i in 0..rolls.len() {
-        for j in 0..rolls[0].len() {
-            if rolls[i][j] {
-                let neighbors = neighbors(rolls, (i, j));
-                if neighbors.iter().filter(|x| **x).count() < 4 {
-                    accessible += 1;
+impl Default for Rules {
+    fn default() -> Self {
+        Self::new(
+            Neighbourhood::Moore,
+            4,
+            Comparison::LessThan,
+            Boundary::Walls,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanOrder {
+    RowMajor,
+    Spiral,
+    Random(u64),
+}
+
+fn scan_order(shape: &[usize], order: ScanOrder) -> Vec<usize> {
+    let len = shape.iter().product();
+    match order {
+        ScanOrder::RowMajor => (0..len).collect(),
+        ScanOrder::Spiral => {
+            let (height, width) = (shape[shape.len() - 2], shape[shape.len() - 1]);
+            let mut cells = Vec::with_capacity(len);
+            for slice in (0..len).step_by((height * width).max(1)) {
+                let cell = |y: usize, x: usize| slice + y * width + x;
+                let (mut top, mut bottom, mut left, mut right) = (0, height, 0, width);
+                while top < bottom && left < right {
+                    cells.extend((left..right).map(|x| cell(top, x)));
+                    cells.extend((top + 1..bottom).map(|y| cell(y, right - 1)));
+                    if top + 1 < bottom {
+                        cells.extend((left..right - 1).rev().map(|x| cell(bottom - 1, x)));
+                    }
+                    if left + 1 < right {
+                        cells.extend((top + 1..bottom - 1).rev().map(|y| cell(y, left)));
+                    }
+                    (top, bottom, left, right) = (top + 1, bottom - 1, left + 1, right - 1);
                 }
             }
+            cells
+        }
+        ScanOrder::Random(mut seed) => {
+            let mut cells = scan_order(shape, ScanOrder::RowMajor);
+            let mut next = || {
+                seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+                let mut z = seed;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                z ^ (z >> 31)
+            };
+            for i in (1..cells.len()).rev() {
+                cells.swap(i, (next() % (i as u64 + 1)) as usize);
+            }
+            cells
         }
     }
-    accessible
 }
 
-fn part2(mut rolls: Vec<Vec<bool>>) -> u64 {
-    let mut accessible = 0;
-    loop {
-        let mut to_remove = Vec::new();
-        for i in 0..rolls.len() {
-            for j in 0..rolls[0].len() {
-                if rolls[i][j] {
-                    let neighbors = neighbors(&rolls, (i, j));
-                    if neighbors.iter().filter(|x| **x).count() < 4 {
-                        accessible += 1;
-                        to_remove.push((i, j))
+fn sequential(lattice: &Lattice, rules: &Rules, order: ScanOrder) -> Vec<Option<u32>> {
+    let order = scan_order(&lattice.shape, order);
+    let offsets = lattice::offsets(rules.neighbourhood, lattice.shape.len());
+    let rolls = lattice.cells();
+    let mut present = rolls.to_vec();
+    let mut counts = lattice.counts(&offsets, rules);
+
+    let mut passes = vec![None; rolls.len()];
+    let mut pass = 0;
+    let mut removed = true;
+    while removed {
+        pass += 1;
+        removed = false;
+        for &index in &order {
+            if present[index] && rules.accessible(counts[index]) {
+                present[index] = false;
+                passes[index] = Some(pass);
+                removed = true;
+                for neighbor in lattice.neighbors(index, &offsets, rules) {
+                    if rolls[neighbor] {
+                        counts[neighbor] -= 1;
                     }
                 }
             }
         }
-        if to_remove.is_empty() {
-            break;
+    }
+    passes
+}
+
+fn last_round(rounds: &[Option<u32>]) -> u32 {
+    rounds.iter().flatten().max().copied().unwrap_or(0)
+}
+
+fn compare_modes(lattice: &Lattice, rules: &Rules, order: ScanOrder) {
+    let simultaneous = lattice.peel(rules);
+    let sequential = sequential(lattice, rules, order);
+    let removed1 = simultaneous.iter().flatten().count();
+    let removed2 = sequential.iter().flatten().count();
+    let differing = simultaneous
+        .iter()
+        .zip(&sequential)
+        .filter(|(a, b)| a.is_some() != b.is_some())
+        .count();
+    println!(
+        "simultaneous: {removed1} removed in {} rounds",
+        last_round(&simultaneous)
+    );
+    println!(
+        "sequential ({order:?}): {removed2} removed in {} passes",
+        last_round(&sequential)
+    );
+    println!(
+        "difference: {} rolls, {differing} cells removed by only one mode",
+        removed2 as i64 - removed1 as i64
+    );
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Cluster {
+    size: usize,
+    low: Vec<usize>,
+    high: Vec<usize>,
+    survivors: usize,
+}
+
+fn clusters(
+    lattice: &Lattice,
+    rules: &Rules,
+    rounds: &[Option<u32>],
+) -> (Vec<Cluster>, Vec<Option<usize>>) {
+    let offsets = lattice::offsets(rules.neighbourhood, lattice.shape.len());
+    let rolls = lattice.cells();
+    let mut labels = vec![None; rolls.len()];
+    let mut clusters = Vec::new();
+    for start in 0..rolls.len() {
+        if !rolls[start] || labels[start].is_some() {
+            continue;
         }
-        for (i, j) in to_remove {
-            rolls[i][j] = false;
+        let mut cluster = Cluster {
+            size: 0,
+            low: lattice.coordinates(start),
+            high: lattice.coordinates(start),
+            survivors: 0,
+        };
+        labels[start] = Some(clusters.len());
+        let mut stack = vec![start];
+        while let Some(index) = stack.pop() {
+            cluster.size += 1;
+            cluster.survivors += usize::from(rounds[index].is_none());
+            for (axis, position) in lattice.coordinates(index).into_iter().enumerate() {
+                cluster.low[axis] = cluster.low[axis].min(position);
+                cluster.high[axis] = cluster.high[axis].max(position);
+            }
+            for neighbor in lattice.neighbors(index, &offsets, rules) {
+                if rolls[neighbor] && labels[neighbor].is_none() {
+                    labels[neighbor] = Some(clusters.len());
+                    stack.push(neighbor);
+                }
+            }
         }
+        clusters.push(cluster);
     }
-    accessible
+    (clusters, labels)
+}
+
+fn cluster_table(clusters: &[Cluster]) -> String {
+    let point = |coordinates: &[usize]| {
+        let coordinates: Vec<_> = coordinates.iter().map(usize::to_string).collect();
+        format!("({})", coordinates.join(","))
+    };
+    let mut table = String::from("cluster    size  bounding box        survivors\n");
+    for (id, cluster) in clusters.iter().enumerate() {
+        table.push_str(&format!(
+            "{:>7}  {:>6}  {:<17}  {:>9}{}\n",
+            id,
+            cluster.size,
+            format!("{}-{}", point(&cluster.low), point(&cluster.high)),
+            cluster.survivors,
+            if cluster.survivors == 0 {
+                "  cleared"
+            } else {
+                ""
+            },
+        ));
+    }
+    let cleared = clusters.iter().filter(|c| c.survivors == 0).count();
+    table.push_str(&format!("{} clusters, {cleared} cleared\n", clusters.len()));
+    table
+}
+
+fn render_clusters(lattice: &Lattice, labels: &[Option<usize>]) -> String {
+    let last = labels.iter().flatten().max().copied().unwrap_or(0);
+    let width = last.to_string().len();
+    let separator = if width > 1 { " " } else { "" };
+    lattice.render(
+        |index| match labels[index] {
+            Some(id) => format!("{id:>width$}"),
+            None => format!("{:>width$}", "."),
+        },
+        separator,
+    )
+}
+
+fn render_rounds(lattice: &Lattice, rounds: &[Option<u32>]) -> String {
+    let rolls = lattice.cells();
+    lattice.render(
+        |index| {
+            match (rolls[index], rounds[index]) {
+                (false, _) => '.',
+                (true, None) => '@',
+                (true, Some(round)) => char::from_digit(round, 36).unwrap_or('+'),
+            }
+            .to_string()
+        },
+        "",
+    )
+}
+
+fn ascii_frame(lattice: &Lattice, rounds: &[Option<u32>], frame: u32) -> String {
+    let rolls = lattice.cells();
+    lattice.render(
+        |index| {
+            match (rolls[index], rounds[index]) {
+                (true, Some(round)) if round == frame => 'x',
+                (true, Some(round)) if round < frame => '.',
+                (true, _) => '@',
+                (false, _) => '.',
+            }
+            .to_string()
+        },
+        "",
+    )
+}
+
+fn animate(lattice: &Lattice, rounds: &[Option<u32>], delay: Duration) {
+    let last = last_round(rounds);
+    let clear = stdout().is_terminal();
+    for frame in 0..=last {
+        if clear {
+            print!("\x1b[2J\x1b[H");
+        }
+        println!("round {frame}/{last}");
+        print!("{}", ascii_frame(lattice, rounds, frame));
+        thread::sleep(delay);
+    }
+}
+
+fn hue(round: u32, last: u32) -> [u8; 3] {
+    let h = 300.0 * (round - 1) as f64 / last.max(2).saturating_sub(1) as f64;
+    let x = 1.0 - ((h / 60.0) % 2.0 - 1.0).abs();
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        _ => (x, 0.0, 1.0),
+    };
+    [r, g, b].map(|c| (c * 255.0).round() as u8)
+}
+
+fn image_frame(lattice: &Lattice, rounds: &[Option<u32>], frame: u32, color: bool) -> String {
+    let last = last_round(rounds);
+    let (height, width) = (lattice.shape[0], lattice.shape[1]);
+    let mut image = if color {
+        format!("P3\n{width} {height}\n255\n")
+    } else {
+        format!("P1\n{width} {height}\n")
+    };
+    let rolls = lattice.cells();
+    for start in (0..rolls.len()).step_by(width.max(1)) {
+        let pixels: Vec<String> = (start..start + width)
+            .map(|index| {
+                let (roll, round) = (rolls[index], rounds[index]);
+                let remaining = roll && round.is_none_or(|round| round > frame);
+                if !color {
+                    return u8::from(remaining).to_string();
+                }
+                let [r, g, b] = match round {
+                    _ if remaining => [0, 0, 0],
+                    Some(round) if roll => hue(round, last),
+                    _ => [255, 255, 255],
+                };
+                format!("{r} {g} {b}")
+            })
+            .collect();
+        image.push_str(&pixels.join(" "));
+        image.push('\n');
+    }
+    image
+}
+
+fn write_frames(lattice: &Lattice, rounds: &[Option<u32>], directory: &Path, color: bool) {
+    assert_eq!(
+        lattice.shape.len(),
+        2,
+        "image frames need a 2-dimensional grid"
+    );
+    let last = last_round(rounds);
+    let extension = if color { "ppm" } else { "pbm" };
+    fs::create_dir_all(directory).unwrap();
+    for frame in 0..=last {
+        let path = directory.join(format!("frame{frame:04}.{extension}"));
+        fs::write(&path, image_frame(lattice, rounds, frame, color))
+            .unwrap_or_else(|error| panic!("cannot write {}: {error}", path.display()));
+    }
+    println!("wrote {} frames to {}", last + 1, directory.display());
+}
+
+fn bitpacked(lattice: &Lattice, rules: &Rules) -> (usize, usize) {
+    let mut grid = BitGrid::from_lattice(lattice);
+    let accessible = grid.accessible(rules);
+    let part1 = accessible.count();
+
+    let mut part2 = 0;
+    let mut accessible = accessible;
+    while accessible.count() > 0 {
+        part2 += accessible.count();
+        grid.remove(&accessible);
+        accessible = grid.accessible(rules);
+    }
+    (part1, part2)
 }
 
 fn main() {
+    let mut neighbourhood = Neighbourhood::Moore;
+    let mut threshold = 4;
+    let mut comparison = Comparison::LessThan;
+    let mut boundary = Boundary::Walls;
+    let mut show_rounds = false;
+    let mut bit_packed = false;
+    let mut delay = None;
+    let mut frames_directory = None;
+    let mut color = true;
+    let mut order = None;
+    let mut show_clusters = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--neighbourhood" => {
+                neighbourhood = match args.next().as_deref() {
+                    Some("von-neumann") => Neighbourhood::VonNeumann,
+                    Some("moore") => Neighbourhood::Moore,
+                    Some(other) if other.starts_with("chebyshev:") => {
+                        Neighbourhood::Chebyshev(other["chebyshev:".len()..].parse().unwrap())
+                    }
+                    other => panic!("unknown neighbourhood {other:?}"),
+                }
+            }
+            "--threshold" => threshold = args.next().and_then(|t| t.parse().ok()).unwrap(),
+            "--at-most" => comparison = Comparison::AtMost,
+            "--boundary" => {
+                boundary = match args.next().as_deref() {
+                    Some("walls") => Boundary::Walls,
+                    Some("wrap-horizontal") => Boundary::WrapHorizontal,
+                    Some("wrap-vertical") => Boundary::WrapVertical,
+                    Some("torus") => Boundary::Torus,
+                    other => panic!("unknown boundary {other:?}"),
+                }
+            }
+            "--rounds" => show_rounds = true,
+            "--bitpacked" => bit_packed = true,
+            "--clusters" => show_clusters = true,
+            "--sequential" => {
+                order = Some(match args.next().as_deref() {
+                    Some("row-major") => ScanOrder::RowMajor,
+                    Some("spiral") => ScanOrder::Spiral,
+                    Some(other) if other.starts_with("random:") => {
+                        ScanOrder::Random(other["random:".len()..].parse().unwrap())
+                    }
+                    other => panic!("unknown scan order {other:?}"),
+                })
+            }
+            "--animate" => {
+                delay = Some(Duration::from_millis(
+                    args.next().and_then(|t| t.parse().ok()).unwrap(),
+                ))
+            }
+            "--frames" => frames_directory = args.next(),
+            "--format" => {
+                color = match args.next().as_deref() {
+                    Some("ppm") => true,
+                    Some("pbm") => false,
+                    other => panic!("unknown image format {other:?}"),
+                }
+            }
+            _ => panic!("unknown argument {arg}"),
+        }
+    }
+    let rules = Rules::new(neighbourhood, threshold, comparison, boundary);
+
     let input = std::fs::read_to_string("input/day04.txt").unwrap();
-    let input = parse(&input);
+    let lattice = Lattice::parse(&input);
 
     let now = Instant::now();
-    let result1 = part1(&input);
+    let result1 = lattice.part1(&rules);
     let time1 = now.elapsed();
 
     let now = Instant::now();
-    let result2 = part2(input);
+    let result2 = lattice.part2(&rules);
     let time2 = now.elapsed();
 
     println!("part1: {result1} after {time1:?}");
     println!("part2: {result2} after {time2:?}");
+
+    if bit_packed {
+        let now = Instant::now();
+        let (bitpacked1, bitpacked2) = bitpacked(&lattice, &rules);
+        let time = now.elapsed();
+        println!("bitpacked: {bitpacked1} {bitpacked2} after {time:?}");
+    }
+
+    if let Some(order) = order {
+        compare_modes(&lattice, &rules, order);
+    }
+
+    if show_clusters {
+        let (clusters, labels) = clusters(&lattice, &rules, &lattice.peel(&rules));
+        print!("{}", cluster_table(&clusters));
+        print!("{}", render_clusters(&lattice, &labels));
+    }
+
+    if show_rounds || delay.is_some() || frames_directory.is_some() {
+        let rounds = lattice.peel(&rules);
+        if show_rounds {
+            print!("{}", render_rounds(&lattice, &rounds));
+        }
+        if let Some(delay) = delay {
+            animate(&lattice, &rounds, delay);
+        }
+        if let Some(directory) = frames_directory {
+            write_frames(&lattice, &rounds, Path::new(&directory), color);
+        }
+    }
+}
+
+mod bitgrid {
+    use super::{Comparison, Lattice, Rules, lattice};
+
+    #[derive(Debug, Clone)]
+    pub struct BitGrid {
+        width: usize,
+        height: usize,
+        stride: usize,
+        words: Vec<u64>,
+    }
+
+    impl BitGrid {
+        pub fn from_lattice(lattice: &Lattice) -> Self {
+            assert_eq!(
+                lattice.shape.len(),
+                2,
+                "the bit-packed grid needs a 2-dimensional grid"
+            );
+            let (height, width) = (lattice.shape[0], lattice.shape[1]);
+            let stride = width.div_ceil(64);
+            let mut words = vec![0; stride * height];
+            for (index, _) in lattice
+                .cells()
+                .iter()
+                .enumerate()
+                .filter(|(_, roll)| **roll)
+            {
+                let (y, x) = (index / width, index % width);
+                words[y * stride + x / 64] |= 1 << (x % 64);
+            }
+            Self {
+                width,
+                height,
+                stride,
+                words,
+            }
+        }
+
+        fn row(&self, y: usize) -> &[u64] {
+            &self.words[y * self.stride..(y + 1) * self.stride]
+        }
+
+        pub fn count(&self) -> usize {
+            self.words
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum()
+        }
+
+        pub fn remove(&mut self, other: &BitGrid) {
+            for (word, removed) in self.words.iter_mut().zip(&other.words) {
+                *word &= !removed;
+            }
+        }
+
+        fn shifted(&self, row: &[u64], w: usize, dx: isize, wrap: bool) -> u64 {
+            let last = self.stride - 1;
+            match dx {
+                0 => row[w],
+                -1 => {
+                    let carry = if w > 0 {
+                        row[w - 1] >> 63
+                    } else if wrap {
+                        row[last] >> ((self.width - 1) % 64) & 1
+                    } else {
+                        0
+                    };
+                    (row[w] << 1) | carry
+                }
+                1 => {
+                    let carry = if w < last {
+                        row[w + 1] << 63
+                    } else if wrap {
+                        (row[0] & 1) << ((self.width - 1) % 64)
+                    } else {
+                        0
+                    };
+                    (row[w] >> 1) | carry
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        pub fn accessible(&self, rules: &Rules) -> BitGrid {
+            let offsets = lattice::offsets(rules.neighbourhood, 2);
+            assert!(
+                offsets.iter().flatten().all(|delta| delta.abs() <= 1),
+                "the bit-packed grid only supports neighbourhoods of radius 1"
+            );
+            let wrap_horizontal = rules.boundary.wraps_horizontally();
+            let wrap_vertical = rules.boundary.wraps_vertically();
+            let threshold = match rules.comparison {
+                Comparison::LessThan => rules.threshold,
+                Comparison::AtMost => rules.threshold + 1,
+            };
+
+            let mut accessible = self.clone();
+            for y in 0..self.height {
+                let rows: Vec<_> = offsets
+                    .iter()
+                    .map(|offset| (offset[0], offset[1]))
+                    .filter(|(dy, dx)| {
+                        !((*dy == 0 || wrap_vertical && self.height == 1)
+                            && (*dx == 0 || wrap_horizontal && self.width == 1))
+                    })
+                    .filter_map(|(dy, dx)| {
+                        let ny = if wrap_vertical {
+                            Some((y as isize + dy).rem_euclid(self.height as isize) as usize)
+                        } else {
+                            y.checked_add_signed(dy).filter(|ny| *ny < self.height)
+                        }?;
+                        Some((self.row(ny), dx))
+                    })
+                    .collect();
+                for w in 0..self.stride {
+                    let mut counter = [0u64; 4];
+                    for &(row, dx) in &rows {
+                        let mut carry = self.shifted(row, w, dx, wrap_horizontal);
+                        for bit in &mut counter {
+                            let next = *bit & carry;
+                            *bit ^= carry;
+                            carry = next;
+                        }
+                    }
+                    accessible.words[y * self.stride + w] &= below(&counter, threshold);
+                }
+            }
+            accessible
+        }
+    }
+
+    fn below(counter: &[u64; 4], threshold: usize) -> u64 {
+        if threshold >= 1 << counter.len() {
+            return !0;
+        }
+        let mut less = 0;
+        let mut equal = !0;
+        for (k, bit) in counter.iter().enumerate().rev() {
+            if threshold >> k & 1 == 1 {
+                less |= equal & !bit;
+                equal &= bit;
+            } else {
+                equal &= !bit;
+            }
+        }
+        less
+    }
+}
+
+mod lattice {
+    use super::{Neighbourhood, Rules};
+    use std::collections::VecDeque;
+
+    pub fn offsets(neighbourhood: Neighbourhood, dimensions: usize) -> Vec<Vec<isize>> {
+        let radius = match neighbourhood {
+            Neighbourhood::VonNeumann | Neighbourhood::Moore => 1,
+            Neighbourhood::Chebyshev(radius) => radius as isize,
+        };
+        let mut offsets = vec![vec![]];
+        for _ in 0..dimensions {
+            offsets = offsets
+                .into_iter()
+                .flat_map(|offset: Vec<isize>| {
+                    (-radius..=radius).map(move |delta| [&offset[..], &[delta]].concat())
+                })
+                .collect();
+        }
+        offsets.retain(|offset| {
+            let moved = offset.iter().filter(|delta| **delta != 0).count();
+            moved > 0 && (neighbourhood != Neighbourhood::VonNeumann || moved == 1)
+        });
+        offsets
+    }
+
+    #[derive(Debug)]
+    pub struct Lattice {
+        pub shape: Vec<usize>,
+        cells: Vec<bool>,
+    }
+
+    impl Lattice {
+        pub fn parse(input: &str) -> Self {
+            if input.trim().is_empty() {
+                return Self {
+                    shape: vec![0, 0],
+                    cells: Vec::new(),
+                };
+            }
+            let mut slices: Vec<Vec<&str>> = vec![vec![]];
+            let mut separators = Vec::new();
+            let mut blank = 0;
+            for line in input.trim().lines().map(str::trim) {
+                if line.is_empty() {
+                    blank += 1;
+                    continue;
+                }
+                if blank > 0 {
+                    separators.push(blank);
+                    slices.push(vec![]);
+                    blank = 0;
+                }
+                slices.last_mut().unwrap().push(line);
+            }
+
+            let levels = separators.iter().copied().max().unwrap_or(0);
+            let mut sizes = vec![1];
+            for level in 1..=levels {
+                let blocks = separators.iter().filter(|s| **s > level).count() + 1;
+                sizes.push(slices.len() / blocks);
+            }
+            for (i, separator) in separators.iter().enumerate() {
+                let expected = (1..=levels)
+                    .rev()
+                    .find(|level| (i + 1).is_multiple_of(sizes[level - 1]))
+                    .unwrap();
+                assert_eq!(
+                    *separator,
+                    expected,
+                    "slice {} does not fit an even lattice",
+                    i + 2
+                );
+            }
+
+            let (height, width) = (slices[0].len(), slices[0][0].len());
+            let mut shape: Vec<usize> = sizes
+                .windows(2)
+                .rev()
+                .map(|pair| pair[1] / pair[0])
+                .collect();
+            shape.extend([height, width]);
+            let mut cells = Vec::new();
+            for (i, slice) in slices.iter().enumerate() {
+                assert_eq!(
+                    slice.len(),
+                    height,
+                    "slice {} has a different height",
+                    i + 1
+                );
+                for line in slice {
+                    assert_eq!(line.len(), width, "slice {} has a different width", i + 1);
+                    cells.extend(line.chars().map(|x| x == '@'));
+                }
+            }
+            Self { shape, cells }
+        }
+
+        pub fn cells(&self) -> &[bool] {
+            &self.cells
+        }
+
+        pub fn coordinates(&self, index: usize) -> Vec<usize> {
+            let mut coordinates = vec![0; self.shape.len()];
+            let mut rest = index;
+            for (axis, size) in self.shape.iter().enumerate().rev() {
+                coordinates[axis] = rest % size;
+                rest /= size;
+            }
+            coordinates
+        }
+
+        pub fn render(&self, cell: impl Fn(usize) -> String, separator: &str) -> String {
+            let width = self.shape[self.shape.len() - 1];
+            let mut rendered = String::new();
+            for (row, start) in (0..self.cells.len()).step_by(width.max(1)).enumerate() {
+                let mut block = 1;
+                for size in self.shape[..self.shape.len() - 1].iter().rev() {
+                    block *= size;
+                    if row == 0 || row % block != 0 {
+                        break;
+                    }
+                    rendered.push('\n');
+                }
+                let cells: Vec<String> = (start..start + width).map(&cell).collect();
+                rendered.push_str(&cells.join(separator));
+                rendered.push('\n');
+            }
+            rendered
+        }
+
+        fn wraps(&self, axis: usize, rules: &Rules) -> bool {
+            let dimensions = self.shape.len();
+            (axis + 1 == dimensions && rules.boundary.wraps_horizontally())
+                || (axis + 2 == dimensions && rules.boundary.wraps_vertically())
+        }
+
+        pub fn neighbors<'a>(
+            &'a self,
+            index: usize,
+            offsets: &'a [Vec<isize>],
+            rules: &'a Rules,
+        ) -> impl Iterator<Item = usize> + 'a {
+            let coordinates = self.coordinates(index);
+            offsets.iter().filter_map(move |offset| {
+                let mut neighbor = 0;
+                for (axis, (&size, &delta)) in self.shape.iter().zip(offset).enumerate() {
+                    let position = if self.wraps(axis, rules) {
+                        (coordinates[axis] as isize + delta).rem_euclid(size as isize) as usize
+                    } else {
+                        coordinates[axis]
+                            .checked_add_signed(delta)
+                            .filter(|p| *p < size)?
+                    };
+                    neighbor = neighbor * size + position;
+                }
+                Some(neighbor).filter(|neighbor| *neighbor != index)
+            })
+        }
+
+        pub fn counts(&self, offsets: &[Vec<isize>], rules: &Rules) -> Vec<usize> {
+            (0..self.cells.len())
+                .map(|index| {
+                    if !self.cells[index] {
+                        return 0;
+                    }
+                    self.neighbors(index, offsets, rules)
+                        .filter(|neighbor| self.cells[*neighbor])
+                        .count()
+                })
+                .collect()
+        }
+
+        pub fn part1(&self, rules: &Rules) -> usize {
+            let counts = self.counts(&offsets(rules.neighbourhood, self.shape.len()), rules);
+            self.cells
+                .iter()
+                .zip(counts)
+                .filter(|(roll, count)| **roll && rules.accessible(*count))
+                .count()
+        }
+
+        pub fn peel(&self, rules: &Rules) -> Vec<Option<u32>> {
+            let offsets = offsets(rules.neighbourhood, self.shape.len());
+            let mut counts = self.counts(&offsets, rules);
+            let mut rounds = vec![None; self.cells.len()];
+            let mut queue = VecDeque::new();
+            for (index, roll) in self.cells.iter().enumerate() {
+                if *roll && rules.accessible(counts[index]) {
+                    rounds[index] = Some(1);
+                    queue.push_back(index);
+                }
+            }
+
+            while let Some(index) = queue.pop_front() {
+                let round = rounds[index].unwrap();
+                for neighbor in self.neighbors(index, &offsets, rules) {
+                    if self.cells[neighbor] && rounds[neighbor].is_none() {
+                        counts[neighbor] -= 1;
+                        if rules.accessible(counts[neighbor]) {
+                            rounds[neighbor] = Some(round + 1);
+                            queue.push_back(neighbor);
+                        }
+                    }
+                }
+            }
+            rounds
+        }
+
+        pub fn part2(&self, rules: &Rules) -> usize {
+            self.peel(rules)
+                .iter()
+                .filter(|round| round.is_some())
+                .count()
+        }
+    }
+}
+
+#[test]
+fn test_boundary() {
+    let lattice = Lattice::parse(_EXAMPLE);
+    let results: Vec<_> = [
+        Boundary::Walls,
+        Boundary::WrapHorizontal,
+        Boundary::WrapVertical,
+        Boundary::Torus,
+    ]
+    .into_iter()
+    .map(|boundary| {
+        let rules = Rules {
+            boundary,
+            ..Rules::default()
+        };
+        let result = (lattice.part1(&rules), lattice.part2(&rules));
+        assert_eq!(bitpacked(&lattice, &rules), result);
+        result
+    })
+    .collect();
+    assert_eq!(results, [(13, 43), (9, 43), (6, 28), (2, 3)]);
+}
+
+#[test]
+fn test_sequential() {
+    let lattice = Lattice::parse(_EXAMPLE);
+    let rules = Rules::default();
+    let removed = |rounds: Vec<Option<u32>>| rounds.iter().map(Option::is_some).collect::<Vec<_>>();
+    for order in [ScanOrder::RowMajor, ScanOrder::Spiral, ScanOrder::Random(7)] {
+        assert_eq!(
+            removed(sequential(&lattice, &rules, order)),
+            removed(lattice.peel(&rules))
+        );
+    }
+    let mut spiral = scan_order(&[3, 4], ScanOrder::Spiral);
+    assert_eq!(spiral[..6], [0, 1, 2, 3, 7, 11]);
+    spiral.sort();
+    assert_eq!(spiral, scan_order(&[3, 4], ScanOrder::RowMajor));
+}
+
+#[test]
+fn test_lattice() {
+    let example = _EXAMPLE.trim();
+    let mirrored: Vec<String> = example
+        .lines()
+        .map(|line| line.chars().rev().collect())
+        .collect();
+    let mirrored = mirrored.join("\n");
+    let rules = Rules::default();
+
+    let lattice = Lattice::parse(&[example, &mirrored, example].join("\n\n"));
+    assert_eq!(lattice.shape, [3, 10, 10]);
+    assert_eq!((lattice.part1(&rules), lattice.part2(&rules)), (2, 2));
+
+    let blocks = [
+        [example, &mirrored].join("\n\n"),
+        [&mirrored, example].join("\n\n"),
+    ];
+    let lattice = Lattice::parse(&blocks.join("\n\n\n"));
+    assert_eq!(lattice.shape, [2, 2, 10, 10]);
+    assert_eq!((lattice.part1(&rules), lattice.part2(&rules)), (0, 0));
+
+    let lattice = Lattice::parse("\n  \n");
+    assert_eq!((lattice.part1(&rules), lattice.part2(&rules)), (0, 0));
 }
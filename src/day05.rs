@@ -1,3 +1,4 @@
+use range_set::RangeSet;
 use std::time::Instant;
 
 const _EXAMPLE: &str = r"3-5
@@ -16,50 +17,251 @@ const _EXAMPLE: &str = r"3-5
 fn parse(input: &str) -> (Vec<(u64, u64)>, Vec<u64>) {
     let (fresh, available) = input.trim().split_once("\n\n").unwrap();
 
-    let fresh = fresh
-        .lines()
-        .map(|line| {
-            let (lo, hi) = line.split_once("-").unwrap();
-            (lo.parse().unwrap(), hi.parse().unwrap())
-        })
-        .collect();
+    let fresh = fresh.lines().filter_map(parse_range).collect();
 
     let available = available.lines().map(|id| id.parse().unwrap()).collect();
 
     (fresh, available)
 }
 
-fn merge_ranges(mut fresh: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
-    fresh.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
-
-    let mut merged: Vec<(u64, u64)> = Vec::new();
-    for range in fresh {
-        if let Some(merged_range) = merged.last_mut()
-            && range.0 <= merged_range.1 + 1
-        {
-            merged_range.1 = merged_range.1.max(range.1);
-        } else {
-            merged.push(range);
+fn parse_range(line: &str) -> Option<(u64, u64)> {
+    let number = |n: &str| {
+        n.parse::<u64>()
+            .unwrap_or_else(|error| panic!("invalid bound '{n}' in range {line}: {error}"))
+    };
+    let (lo, hi) = if let Some((lo, hi)) = line.split_once("..") {
+        let lo = number(lo);
+        match hi {
+            "" => (lo, u64::MAX),
+            _ if hi.starts_with("=") => (lo, number(&hi[1..])),
+            _ => {
+                let hi = number(hi);
+                if lo == hi {
+                    return None;
+                }
+                (
+                    lo,
+                    hi.checked_sub(1)
+                        .unwrap_or_else(|| panic!("inverted range {line}")),
+                )
+            }
         }
+    } else {
+        let (lo, hi) = line
+            .split_once("-")
+            .unwrap_or_else(|| panic!("malformed range {line}"));
+        match hi {
+            "" => (number(lo), u64::MAX),
+            _ => (number(lo), number(hi)),
+        }
+    };
+    assert!(lo <= hi, "inverted range {line}");
+    Some((lo, hi))
+}
+
+mod range_set {
+    #![allow(dead_code)]
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct RangeSet {
+        ranges: Vec<(u64, u64)>,
+    }
+
+    impl RangeSet {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn ranges(&self) -> &[(u64, u64)] {
+            &self.ranges
+        }
+
+        pub fn insert(&mut self, (lo, hi): (u64, u64)) {
+            assert!(lo <= hi, "inverted range {lo}-{hi}");
+            let start = self
+                .ranges
+                .partition_point(|range| range.1.saturating_add(1) < lo);
+            let end = self
+                .ranges
+                .partition_point(|range| range.0 <= hi.saturating_add(1));
+            let mut merged = (lo, hi);
+            if start < end {
+                merged.0 = merged.0.min(self.ranges[start].0);
+                merged.1 = merged.1.max(self.ranges[end - 1].1);
+            }
+            self.ranges.splice(start..end, [merged]);
+        }
+
+        pub fn remove(&mut self, (lo, hi): (u64, u64)) {
+            assert!(lo <= hi, "inverted range {lo}-{hi}");
+            let start = self.ranges.partition_point(|range| range.1 < lo);
+            let end = self.ranges.partition_point(|range| range.0 <= hi);
+            if start == end {
+                return;
+            }
+            let (first, last) = (self.ranges[start], self.ranges[end - 1]);
+            let mut rest = Vec::new();
+            if first.0 < lo {
+                rest.push((first.0, lo - 1));
+            }
+            if last.1 > hi {
+                rest.push((hi + 1, last.1));
+            }
+            self.ranges.splice(start..end, rest);
+        }
+
+        pub fn contains(&self, id: u64) -> bool {
+            let index = self.ranges.partition_point(|range| range.1 < id);
+            self.ranges.get(index).is_some_and(|range| range.0 <= id)
+        }
+
+        pub fn len(&self) -> u128 {
+            self.ranges
+                .iter()
+                .map(|(lo, hi)| (hi - lo) as u128 + 1)
+                .sum()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.ranges.is_empty()
+        }
+
+        pub fn union(&self, other: &RangeSet) -> RangeSet {
+            self.ranges.iter().chain(&other.ranges).copied().collect()
+        }
+
+        pub fn intersection(&self, other: &RangeSet) -> RangeSet {
+            let mut ranges = Vec::new();
+            let (mut i, mut j) = (0, 0);
+            while i < self.ranges.len() && j < other.ranges.len() {
+                let (a, b) = (self.ranges[i], other.ranges[j]);
+                let (lo, hi) = (a.0.max(b.0), a.1.min(b.1));
+                if lo <= hi {
+                    ranges.push((lo, hi));
+                }
+                if a.1 < b.1 {
+                    i += 1;
+                } else {
+                    j += 1;
+                }
+            }
+            RangeSet { ranges }
+        }
+
+        pub fn difference(&self, other: &RangeSet) -> RangeSet {
+            let mut difference = self.clone();
+            for range in &other.ranges {
+                difference.remove(*range);
+            }
+            difference
+        }
+
+        pub fn complement(&self, (lo, hi): (u64, u64)) -> RangeSet {
+            let mut complement = RangeSet::new();
+            complement.insert((lo, hi));
+            complement.difference(self)
+        }
+    }
+
+    impl FromIterator<(u64, u64)> for RangeSet {
+        fn from_iter<I: IntoIterator<Item = (u64, u64)>>(iter: I) -> Self {
+            let mut fresh: Vec<_> = iter.into_iter().collect();
+            if let Some((lo, hi)) = fresh.iter().find(|(lo, hi)| lo > hi) {
+                panic!("inverted range {lo}-{hi}");
+            }
+            fresh.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+            let mut ranges: Vec<(u64, u64)> = Vec::new();
+            for range in fresh {
+                if let Some(merged_range) = ranges.last_mut()
+                    && range.0 <= merged_range.1.saturating_add(1)
+                {
+                    merged_range.1 = merged_range.1.max(range.1);
+                } else {
+                    ranges.push(range);
+                }
+            }
+            RangeSet { ranges }
+        }
+    }
+
+    #[test]
+    fn test() {
+        let mut state = 1u64;
+        let mut random = |n: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % n
+        };
+        let bits = |set: &RangeSet| (0..70).map(|id| set.contains(id)).collect::<Vec<_>>();
+
+        for _ in 0..200 {
+            let a: RangeSet = (0..4)
+                .map(|_| {
+                    let lo = random(60);
+                    (lo, lo + random(8))
+                })
+                .collect();
+            let mut b = RangeSet::new();
+            let mut expected = vec![false; 70];
+            for _ in 0..6 {
+                let lo = random(60);
+                let range = (lo, lo + random(8));
+                let inserting = random(3) > 0;
+                if inserting {
+                    b.insert(range);
+                } else {
+                    b.remove(range);
+                }
+                for id in range.0..=range.1 {
+                    expected[id as usize] = inserting;
+                }
+            }
+            assert_eq!(bits(&b), expected);
+            assert!(b.ranges.windows(2).all(|pair| pair[0].1 + 1 < pair[1].0));
+            assert_eq!(b.len(), expected.iter().filter(|x| **x).count() as u128);
+
+            let (a_bits, b_bits) = (bits(&a), bits(&b));
+            let combine = |f: fn(bool, bool) -> bool| {
+                a_bits
+                    .iter()
+                    .zip(&b_bits)
+                    .map(|(x, y)| f(*x, *y))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(bits(&a.union(&b)), combine(|x, y| x || y));
+            assert_eq!(bits(&a.intersection(&b)), combine(|x, y| x && y));
+            assert_eq!(bits(&a.difference(&b)), combine(|x, y| x && !y));
+            let complement = a.complement((10, 50));
+            let in_bounds = (0..70).map(|id| (10..=50).contains(&id) && !a_bits[id as usize]);
+            assert_eq!(bits(&complement), in_bounds.collect::<Vec<_>>());
+        }
+
+        let mut full: RangeSet = [(u64::MAX - 1, u64::MAX), (0, u64::MAX - 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(full.ranges(), [(0, u64::MAX)]);
+        assert_eq!(full.len(), 1 << 64);
+        full.remove((u64::MAX, u64::MAX));
+        full.insert((u64::MAX, u64::MAX));
+        assert_eq!(full.ranges(), [(0, u64::MAX)]);
+        assert_eq!(full.complement((0, u64::MAX)), RangeSet::new());
     }
-    merged
 }
 
-fn part1(fresh: &[(u64, u64)], available: &[u64]) -> usize {
-    available
-        .iter()
-        .filter(|id| fresh.iter().any(|(lo, hi)| lo <= *id && *id <= hi))
-        .count()
+fn part1(fresh: &RangeSet, available: &[u64]) -> usize {
+    available.iter().filter(|id| fresh.contains(**id)).count()
 }
 
-fn part2(fresh: &[(u64, u64)]) -> u64 {
-    fresh.iter().map(|(lo, hi)| hi - lo + 1).sum()
+fn part2(fresh: &RangeSet) -> u128 {
+    fresh.len()
 }
 
 fn main() {
     let input = std::fs::read_to_string("input/day05.txt").unwrap();
     let (fresh, available) = parse(&input);
-    let fresh = merge_ranges(fresh);
+    let fresh: RangeSet = fresh.into_iter().collect();
 
     let now = Instant::now();
     let result1 = part1(&fresh, &available);
@@ -72,3 +274,15 @@ fn main() {
     println!("part1: {result1} after {time1:?}");
     println!("part2: {result2} after {time2:?}");
 }
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(parse_range("10-20"), Some((10, 20)));
+    assert_eq!(parse_range("10..20"), Some((10, 19)));
+    assert_eq!(parse_range("10..=20"), Some((10, 20)));
+    assert_eq!(parse_range("10..10"), None);
+    assert_eq!(parse_range("100-"), Some((100, u64::MAX)));
+    assert_eq!(parse_range("100.."), Some((100, u64::MAX)));
+    assert!(std::panic::catch_unwind(|| parse_range("20-10")).is_err());
+    assert!(std::panic::catch_unwind(|| parse_range("20..10")).is_err());
+}